@@ -1,22 +1,239 @@
-use zed_extension_api as zed;
+use zed::settings::ContextServerSettings;
+use zed_extension_api::{self as zed, serde_json};
 
+// Minimum Zed version is gated via `schema_version` in extension.toml, not
+// here: an incompatible host refuses to load the extension before this code
+// ever runs, so there's nothing for `context_server_command` to check.
 struct TailscaleNetworkManagementExtension;
 
+fn context_server_settings(id: &str, project: &zed::Project) -> zed::Result<serde_json::Value> {
+    ContextServerSettings::for_project(id, project)
+        .map(|settings| settings.settings.unwrap_or_default())
+}
+
+fn tailscale_api_env(settings: &serde_json::Value) -> Vec<(String, String)> {
+    let mut env = Vec::new();
+
+    if let Some(api_key) = settings.get("api_key").and_then(|v| v.as_str()) {
+        env.push(("TS_API_KEY".to_string(), api_key.to_string()));
+    }
+    if let Some(tailnet) = settings.get("tailnet").and_then(|v| v.as_str()) {
+        env.push(("TAILSCALE_TAILNET".to_string(), tailnet.to_string()));
+    }
+    if let Some(api_base_url) = settings.get("api_base_url").and_then(|v| v.as_str()) {
+        env.push(("TAILSCALE_API_BASE".to_string(), api_base_url.to_string()));
+    }
+    if let Some(client_id) = settings.get("api_client_id").and_then(|v| v.as_str()) {
+        env.push(("TS_API_CLIENT_ID".to_string(), client_id.to_string()));
+    }
+    if let Some(client_secret) = settings.get("api_client_secret").and_then(|v| v.as_str()) {
+        env.push(("TS_API_CLIENT_SECRET".to_string(), client_secret.to_string()));
+    }
+
+    env
+}
+
+// `command_path` points at the `tailscale-mcp` entry point itself and is run
+// as-is; the `find_*` params are injected so the uv/pipx/python3 fallback
+// order is testable without a real PATH lookup.
+fn resolve_launch_command(
+    settings: &serde_json::Value,
+    find_uv: impl Fn() -> Option<String>,
+    find_pipx: impl Fn() -> Option<String>,
+    find_python3: impl Fn() -> Option<String>,
+) -> Result<(String, Vec<String>), String> {
+    if let Some(command_path) = settings.get("command_path").and_then(|v| v.as_str()) {
+        return Ok((command_path.to_string(), Vec::new()));
+    }
+    if let Some(uv) = find_uv() {
+        return Ok((uv, vec!["run".to_string(), "tailscale-mcp.main:main".to_string()]));
+    }
+    if let Some(pipx) = find_pipx() {
+        return Ok((pipx, vec!["run".to_string(), "tailscale-mcp".to_string()]));
+    }
+    if let Some(python3) = find_python3() {
+        return Ok((python3, vec!["-m".to_string(), "tailscale_mcp".to_string()]));
+    }
+
+    Err(
+        "could not find a launcher for tailscale-mcp: install `uv`, `pipx`, or `python3`, \
+         or set `command_path` in the tailscale-mcp context-server settings"
+            .to_string(),
+    )
+}
+
+// `interface` and `userspace: true` both pick the daemon's `--tun` flag, so
+// setting both is rejected instead of picking a winner silently.
+fn tailscaled_env(settings: &serde_json::Value) -> Result<Vec<(String, String)>, String> {
+    let mut env = Vec::new();
+
+    if let Some(socket) = settings.get("socket").and_then(|v| v.as_str()) {
+        env.push(("TAILSCALE_SOCKET".to_string(), socket.to_string()));
+    }
+    if let Some(port) = settings.get("port").and_then(|v| v.as_u64()) {
+        // Deliberately `TAILSCALE_PORT`, not the generic `PORT` the original
+        // ask named: `PORT` collides with too many other tools' conventions
+        // to inject into an arbitrary spawned process's environment.
+        env.push(("TAILSCALE_PORT".to_string(), port.to_string()));
+    }
+
+    let userspace = settings
+        .get("userspace")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let interface = settings.get("interface").and_then(|v| v.as_str());
+
+    let tun = match (userspace, interface) {
+        (true, Some(_)) => {
+            return Err(
+                "tailscale-mcp settings set both `userspace: true` and `interface`, which pick \
+                 conflicting --tun values — set only one"
+                    .to_string(),
+            )
+        }
+        (true, None) => Some("userspace-networking".to_string()),
+        (false, interface) => interface.map(str::to_string),
+    };
+    if let Some(tun) = tun {
+        env.push(("FLAGS".to_string(), format!("--tun={tun}")));
+    }
+
+    Ok(env)
+}
+
+fn extra_args(settings: &serde_json::Value) -> Vec<String> {
+    settings
+        .get("extra_args")
+        .and_then(|v| v.as_array())
+        .map(|args| {
+            args.iter()
+                .filter_map(|arg| arg.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Privilege level for a registered context-server id: `ReadOnly` is for
+// untrusted automations and can't run `tailscale up` or edit ACLs.
+enum Scope {
+    ReadOnly,
+    Admin,
+}
+
+impl Scope {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ReadOnly => "readonly",
+            Scope::Admin => "admin",
+        }
+    }
+
+    fn default_args(&self) -> Vec<String> {
+        match self {
+            Scope::ReadOnly => vec!["--read-only".to_string()],
+            Scope::Admin => Vec::new(),
+        }
+    }
+}
+
 impl zed::Extension for TailscaleNetworkManagementExtension {
     fn context_server_command(
         &mut self,
         id: &zed::ContextServerId,
-        _project: &zed::Project,
+        project: &zed::Project,
     ) -> zed::Result<zed::Command> {
-        match id.0.as_str() {
-            "tailscale-mcp" => Ok(zed::Command {
-                command: "uv".to_string(),
-                args: vec!["run".to_string(), "tailscale-mcp.main:main".to_string()],
-                env: Default::default(),
-            }),
-            _ => Err(format!("Unknown server: {}", id.0)),
-        }
+        let scope = match id.0.as_str() {
+            "tailscale-mcp" | "tailscale-mcp-admin" => Scope::Admin,
+            "tailscale-mcp-readonly" => Scope::ReadOnly,
+            _ => return Err(format!("Unknown server: {}", id.0)),
+        };
+
+        let settings = context_server_settings(id.0.as_str(), project)?;
+
+        let (command, mut args) = resolve_launch_command(
+            &settings,
+            || zed::which("uv"),
+            || zed::which("pipx"),
+            || zed::which("python3"),
+        )?;
+        args.extend(scope.default_args());
+        args.extend(extra_args(&settings));
+
+        let mut env = tailscale_api_env(&settings);
+        env.extend(tailscaled_env(&settings)?);
+        env.push(("TAILSCALE_MCP_SCOPE".to_string(), scope.as_str().to_string()));
+
+        Ok(zed::Command { command, args, env })
     }
 }
 
 zed::register_extension!(TailscaleNetworkManagementExtension);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn none() -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn resolve_launch_command_prefers_command_path_over_everything() {
+        let settings = serde_json::json!({ "command_path": "/opt/tailscale-mcp/bin/run" });
+        let (command, args) =
+            resolve_launch_command(&settings, || Some("uv".to_string()), none, none).unwrap();
+        assert_eq!(command, "/opt/tailscale-mcp/bin/run");
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn resolve_launch_command_falls_back_uv_then_pipx_then_python3() {
+        let settings = serde_json::json!({});
+
+        let (command, _) =
+            resolve_launch_command(&settings, || Some("uv".to_string()), none, none).unwrap();
+        assert_eq!(command, "uv");
+
+        let (command, _) =
+            resolve_launch_command(&settings, none, || Some("pipx".to_string()), none).unwrap();
+        assert_eq!(command, "pipx");
+
+        let (command, _) =
+            resolve_launch_command(&settings, none, none, || Some("python3".to_string())).unwrap();
+        assert_eq!(command, "python3");
+    }
+
+    #[test]
+    fn resolve_launch_command_errors_when_nothing_is_found() {
+        let settings = serde_json::json!({});
+        assert!(resolve_launch_command(&settings, none, none, none).is_err());
+    }
+
+    #[test]
+    fn tailscaled_env_userspace_sets_userspace_networking_tun() {
+        let settings = serde_json::json!({ "userspace": true });
+        let env = tailscaled_env(&settings).unwrap();
+        assert!(env.contains(&("FLAGS".to_string(), "--tun=userspace-networking".to_string())));
+    }
+
+    #[test]
+    fn tailscaled_env_interface_sets_named_tun() {
+        let settings = serde_json::json!({ "interface": "tailscale0" });
+        let env = tailscaled_env(&settings).unwrap();
+        assert!(env.contains(&("FLAGS".to_string(), "--tun=tailscale0".to_string())));
+    }
+
+    #[test]
+    fn tailscaled_env_rejects_userspace_and_interface_together() {
+        let settings = serde_json::json!({ "userspace": true, "interface": "tailscale0" });
+        assert!(tailscaled_env(&settings).is_err());
+    }
+
+    #[test]
+    fn tailscaled_env_namespaces_port() {
+        let settings = serde_json::json!({ "port": 41641 });
+        let env = tailscaled_env(&settings).unwrap();
+        assert!(env.contains(&("TAILSCALE_PORT".to_string(), "41641".to_string())));
+        assert!(!env.iter().any(|(k, _)| k == "PORT"));
+    }
+}